@@ -1,5 +1,5 @@
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     env::current_exe,
     time::{Duration, Instant},
 };
@@ -22,6 +22,22 @@ const SCALING: u32 = 8;
 const MAX_FIELD: f32 = 5.0;
 const DOWNSCALE_COUNT: u32 = 7;
 const CHARGE: f32 = 5.0;
+const FLUID_VISCOSITY: f32 = 0.0001;
+const FLOCK_RADIUS: i32 = 5;
+const FLOCK_SEPARATION_RADIUS: f32 = 2.0;
+const FLOCK_MAX_SPEED: f32 = 2.0;
+const FLOCK_WEIGHT_STEP: f32 = 0.1;
+const MAX_SOFT_PARTICLES: u32 = 256;
+const MAX_SPRINGS: u32 = 512;
+const SPRING_STIFFNESS: f32 = 40.0;
+const SPRING_DAMPING: f32 = 2.0;
+const SOFT_BODY_MASS: f32 = 1.0;
+// Explicit/Verlet spring integration is only stable for dt < 2*sqrt(m/k); with
+// SPRING_STIFFNESS/SOFT_BODY_MASS above that's ~0.316, far below the `step` (10.0) used as `dt`
+// elsewhere in this file. So the soft-body integrator substeps with its own small fixed dt instead
+// of `step`, relaxing the springs once per substep.
+const SOFT_BODY_SUBSTEPS: u32 = 8;
+const SOFT_BODY_SUBSTEP_DT: f32 = 1.0 / 60.0;
 
 #[derive(Debug, Clone, Copy, Value)]
 #[repr(u32)]
@@ -30,6 +46,7 @@ pub enum View {
     Divergence = 1,
     Curl = 2,
     TrailOnly = 3,
+    Density = 4,
 }
 impl View {
     fn next(&mut self) {
@@ -37,11 +54,22 @@ impl View {
             Self::Field => Self::Divergence,
             Self::Divergence => Self::Curl,
             Self::Curl => Self::TrailOnly,
-            Self::TrailOnly => Self::Field,
+            Self::TrailOnly => Self::Density,
+            Self::Density => Self::Field,
         }
     }
 }
 
+// A spring in the soft-body network: indices of the two linked particles and the rest length to
+// relax toward.
+#[derive(Debug, Clone, Copy, Value)]
+#[repr(C)]
+pub struct Spring {
+    a: u32,
+    b: u32,
+    rest_length: f32,
+}
+
 #[derive(Debug, Clone, Copy)]
 struct Runtime {
     cursor_pos: PhysicalPosition<f64>,
@@ -49,6 +77,12 @@ struct Runtime {
     viewed_layer: u32,
     view: View,
     activate_multigrid: bool,
+    activate_advection: bool,
+    fluid_mode: bool,
+    flock_separation_weight: f32,
+    flock_alignment_weight: f32,
+    flock_cohesion_weight: f32,
+    soft_body_mode: bool,
 }
 impl Default for Runtime {
     fn default() -> Self {
@@ -58,6 +92,12 @@ impl Default for Runtime {
             viewed_layer: 0,
             view: View::Field,
             activate_multigrid: true,
+            activate_advection: false,
+            fluid_mode: false,
+            flock_separation_weight: 0.0,
+            flock_alignment_weight: 0.0,
+            flock_cohesion_weight: 0.0,
+            soft_body_mode: false,
         }
     }
 }
@@ -152,11 +192,66 @@ fn main() {
         DOWNSCALE_COUNT,
         1,
     );
+    // Painted obstacle cells, same layout as `charges`/`magnets` so the multigrid solver can
+    // consult them at every level.
+    let solids = device.create_tex3d::<u32>(
+        PixelStorage::Byte1,
+        GRID_SIZE,
+        GRID_SIZE,
+        DOWNSCALE_COUNT,
+        1,
+    );
 
     let particles = device.create_tex2d::<u32>(PixelStorage::Byte1, GRID_SIZE, GRID_SIZE, 1);
     let particle_velocity =
         device.create_tex2d::<Vec2<f32>>(PixelStorage::Float2, GRID_SIZE, GRID_SIZE, 1);
     let trail = device.create_tex2d::<f32>(PixelStorage::Float1, GRID_SIZE, GRID_SIZE, 1);
+    // Destination buffers for the semi-Lagrangian advection of `particle_velocity` and `trail`.
+    // Gather-based advection reads several neighboring cells per write, so it needs a separate
+    // texture to avoid reading values that another thread has already overwritten this frame.
+    let particle_velocity_next =
+        device.create_tex2d::<Vec2<f32>>(PixelStorage::Float2, GRID_SIZE, GRID_SIZE, 1);
+    let trail_next = device.create_tex2d::<f32>(PixelStorage::Float1, GRID_SIZE, GRID_SIZE, 1);
+
+    // Stable-fluids subsystem: a genuine incompressible Navier-Stokes velocity field, independent
+    // of the particle occupancy grid, that reuses the multigrid divergence relaxation above
+    // (`field`/`charges`/compute_divergence/apply_deltas) as its pressure-projection step.
+    let fluid_velocity =
+        device.create_tex2d::<Vec2<f32>>(PixelStorage::Float2, GRID_SIZE, GRID_SIZE, 1);
+    let fluid_velocity_prev =
+        device.create_tex2d::<Vec2<f32>>(PixelStorage::Float2, GRID_SIZE, GRID_SIZE, 1);
+    let fluid_velocity_next =
+        device.create_tex2d::<Vec2<f32>>(PixelStorage::Float2, GRID_SIZE, GRID_SIZE, 1);
+    let fluid_density = device.create_tex2d::<f32>(PixelStorage::Float1, GRID_SIZE, GRID_SIZE, 1);
+    let fluid_density_next =
+        device.create_tex2d::<f32>(PixelStorage::Float1, GRID_SIZE, GRID_SIZE, 1);
+    // The fluid pressure projection below reuses the electromagnetic `charges`/`field`/`magnets`
+    // textures and the shared multigrid kernels as its Poisson solve, so it has to save and
+    // restore whatever those hold across the frame instead of leaving them clobbered: the painted
+    // charge/magnet at level 0 (the only persistent level; the V-cycle rederives the rest from it
+    // every call) and `field` at every level (which *is* persistent, evolving by relaxation rather
+    // than being recomputed from scratch each frame).
+    let charges_snapshot =
+        device.create_tex2d::<f32>(PixelStorage::Float1, GRID_SIZE, GRID_SIZE, 1);
+    let magnets_snapshot =
+        device.create_tex2d::<f32>(PixelStorage::Float1, GRID_SIZE, GRID_SIZE, 1);
+    let field_snapshot = device.create_tex3d::<Vec2<f32>>(
+        PixelStorage::Float2,
+        GRID_SIZE + 1,
+        GRID_SIZE + 1,
+        DOWNSCALE_COUNT,
+        1,
+    );
+
+    // Soft-body subsystem: particles placed and linked into a spring network, integrated with
+    // Verlet. Unlike the occupancy grid above, these are explicit indexed nodes (so springs can
+    // reference the two particles they connect) rather than anonymous bits in a texture.
+    let soft_position = device.create_buffer::<Vec2<f32>>(MAX_SOFT_PARTICLES as usize);
+    let soft_position_prev = device.create_buffer::<Vec2<f32>>(MAX_SOFT_PARTICLES as usize);
+    // Force accumulator, split into components since atomics aren't available on vector buffers.
+    let soft_force_x = device.create_buffer::<f32>(MAX_SOFT_PARTICLES as usize);
+    let soft_force_y = device.create_buffer::<f32>(MAX_SOFT_PARTICLES as usize);
+    let springs = device.create_buffer::<Spring>(MAX_SPRINGS as usize);
 
     let draw_kernel = Kernel::<fn(u32, View)>::new(
         &device,
@@ -189,6 +284,9 @@ fn main() {
                 } else if view.as_u32() == View::TrailOnly.expr().as_u32() {
                     let t = trail.read(pos);
                     Vec3::splat_expr(t)
+                } else if view.as_u32() == View::Density.expr().as_u32() {
+                    let d = fluid_density.read(pos);
+                    Vec3::expr(0.0, d, d)
                 } else if view.as_u32() == View::Field.expr().as_u32() {
                     let f = field.read(field_pos) / (1 << layer).as_f32();
                     (f / (MAX_FIELD * 2.0) + 0.5).extend(0.0)
@@ -212,12 +310,40 @@ fn main() {
         &device,
         &track!(|level, offset| {
             let pos = dispatch_id().xy();
+            // A solid cell has no fluid in it at all, so it contributes no divergence target.
+            if solids.read(pos.extend(level)) == 1 {
+                divergence_error.write(pos.extend(level), 0.0);
+                return;
+            }
             let target_divergence = charges.read(pos.extend(level)) * 1.0;
             let f = field.read(pos.extend(level));
-            let l = -f.x;
-            let u = -f.y;
-            let r = field.read((pos + Vec2::x()).extend(level)).x;
-            let d = field.read((pos + Vec2::y()).extend(level)).y;
+            // Zero-normal-flux (Neumann) boundary: a face shared with a solid neighbor carries no
+            // flow, so its term is dropped from the divergence stencil instead of being read from
+            // `field`.
+            let l = if pos.x > 0 && solids.read((pos - Vec2::x()).extend(level)) == 1 {
+                0.0_f32.expr()
+            } else {
+                -f.x
+            };
+            let u = if pos.y > 0 && solids.read((pos - Vec2::y()).extend(level)) == 1 {
+                0.0_f32.expr()
+            } else {
+                -f.y
+            };
+            // `solids` is sized GRID_SIZE (not padded like `field`), so the neighbor read below
+            // would run one texel past its valid range at the domain's far edge; guard it the same
+            // way `apply_deltas` guards its own solids reads against `level_size`.
+            let level_size = GRID_SIZE >> level;
+            let r = if pos.x + 1 < level_size && solids.read((pos + Vec2::x()).extend(level)) == 1 {
+                0.0_f32.expr()
+            } else {
+                field.read((pos + Vec2::x()).extend(level)).x
+            };
+            let d = if pos.y + 1 < level_size && solids.read((pos + Vec2::y()).extend(level)) == 1 {
+                0.0_f32.expr()
+            } else {
+                field.read((pos + Vec2::y()).extend(level)).y
+            };
             let divergence = r + d + l + u;
             let error = divergence - target_divergence;
 
@@ -237,6 +363,17 @@ fn main() {
         &device,
         &track!(|level| {
             let pos = dispatch_id().xy();
+            // This node sits at the shared corner of the four solids cells below; if any of them
+            // is an obstacle, leave the tangential correction at zero rather than dragging field
+            // through (or along the face of) the solid.
+            let touches_solid = solids.read(pos.extend(level)) == 1
+                || solids.read((pos + Vec2::x()).extend(level)) == 1
+                || solids.read((pos + Vec2::y()).extend(level)) == 1
+                || solids.read((pos + 1).extend(level)) == 1;
+            if touches_solid {
+                curl_error.write(pos.extend(level), 0.0);
+                return;
+            }
             let target_curl = magnets.read(pos.extend(level)) * 1.0;
             let f = field.read((pos + 1).extend(level));
 
@@ -276,13 +413,89 @@ fn main() {
             if pos.y > 0 && (pos.x + 1) < GRID_SIZE << level && pos.y < GRID_SIZE << level {
                 *delta.y -= -curl_error.read((pos - Vec2::y()).extend(level));
             }
-            field.write(
-                pos.extend(level),
-                field.read(pos.extend(level)) + delta / 4.0,
+            // This node's x component is the face shared by the solids cells to its left and at
+            // its own position; its y component is shared with the cells above and at its own
+            // position. Either side being solid means zero normal flux through that face.
+            let level_size = GRID_SIZE >> level;
+            let x_blocked = (pos.x > 0 && solids.read((pos - Vec2::x()).extend(level)) == 1)
+                || (pos.x < level_size && solids.read(pos.extend(level)) == 1);
+            let y_blocked = (pos.y > 0 && solids.read((pos - Vec2::y()).extend(level)) == 1)
+                || (pos.y < level_size && solids.read(pos.extend(level)) == 1);
+            let new_field = field.read(pos.extend(level)) + delta / 4.0;
+            let new_field = Vec2::expr(
+                if x_blocked {
+                    0.0_f32.expr()
+                } else {
+                    new_field.x
+                },
+                if y_blocked {
+                    0.0_f32.expr()
+                } else {
+                    new_field.y
+                },
             );
+            field.write(pos.extend(level), new_field);
         }),
     );
 
+    // Reynolds-style boid steering: each occupied cell scans a local window of neighboring
+    // particles and nudges `particle_velocity` toward separation, alignment and cohesion.
+    // Runs before `update_kernel` so the steered velocity is what actually moves the particle
+    // this frame; the electromagnetic `field` remains a separate, additive force in that kernel.
+    let flock_kernel = Kernel::<fn(f32, f32, f32)>::new(
+        &device,
+        &track!(|w_sep, w_ali, w_coh| {
+            let pos = dispatch_id().xy();
+            if particles.read(pos) != 1 {
+                return;
+            }
+            let self_pos_i = pos.cast_i32();
+            let self_vel = particle_velocity.read(pos);
+
+            let sep = Vec2::splat(0.0_f32).var();
+            let ali = Vec2::splat(0.0_f32).var();
+            let centroid = Vec2::splat(0.0_f32).var();
+            let count = 0.0_f32.var();
+
+            for dy in -FLOCK_RADIUS..=FLOCK_RADIUS {
+                for dx in -FLOCK_RADIUS..=FLOCK_RADIUS {
+                    if dx == 0 && dy == 0 {
+                        continue;
+                    }
+                    let n = self_pos_i + Vec2::expr(dx, dy);
+                    if n.x < 0 || n.x >= GRID_SIZE as i32 || n.y < 0 || n.y >= GRID_SIZE as i32 {
+                        continue;
+                    }
+                    let n = n.cast_u32();
+                    if particles.read(n) != 1 {
+                        continue;
+                    }
+                    let offset = Vec2::expr(dx as f32, dy as f32);
+                    let dist = offset.length();
+                    if dist < FLOCK_SEPARATION_RADIUS {
+                        *sep -= offset / (dist * dist);
+                    }
+                    *ali += particle_velocity.read(n);
+                    *centroid += offset;
+                    *count += 1.0;
+                }
+            }
+
+            if count > 0.0 {
+                let ali_dir = ali / count - self_vel;
+                let coh_dir = centroid / count;
+                let steer = sep * w_sep + ali_dir * w_ali + coh_dir * w_coh;
+                let new_vel = self_vel + steer;
+                let speed = new_vel.length();
+                let new_vel = if speed > FLOCK_MAX_SPEED {
+                    new_vel / speed * FLOCK_MAX_SPEED
+                } else {
+                    new_vel
+                };
+                particle_velocity.write(pos, new_vel);
+            }
+        }),
+    );
     let update_kernel = Kernel::<fn(f32, u32)>::new(
         &device,
         &track!(|dt, t| {
@@ -315,7 +528,23 @@ fn main() {
                     return;
                 }
                 let new_pos = new_pos.cast_u32();
-                if (new_pos != pos).any() {
+                if solids.read(new_pos.extend(0)) == 1 {
+                    // Blocked: reflect the velocity component(s) that would have carried the
+                    // particle into the solid face instead of moving it there.
+                    let reflect = Vec2::expr(
+                        if new_pos.x != pos.x {
+                            -1.0_f32
+                        } else {
+                            1.0_f32
+                        },
+                        if new_pos.y != pos.y {
+                            -1.0_f32
+                        } else {
+                            1.0_f32
+                        },
+                    );
+                    particle_velocity.write(pos, vel * reflect);
+                } else if (new_pos != pos).any() {
                     particles.write(new_pos, 1);
                     // particle_velocity.write(new_pos, vel);
                     particles.write(pos, 0);
@@ -325,6 +554,57 @@ fn main() {
             }
         }),
     );
+    // Jos-Stam style semi-Lagrangian backtrace: each destination cell gathers its value from where
+    // it "came from" under the velocity field, bilinearly interpolating between the four
+    // surrounding source cells, instead of scattering to an integer-rounded destination (noisy, and
+    // can leave holes or collisions). This is the gather formulation, so every cell can be written
+    // independently with no write contention. It's an alternative way of carrying
+    // `particle_velocity`/`trail` forward each frame for comparison against the default; it doesn't
+    // touch `particles` itself, which always still moves via `update_kernel`'s stochastic walk.
+    let advect_kernel = Kernel::<fn(f32)>::new(
+        &device,
+        &track!(|dt| {
+            let pos = dispatch_id().xy();
+            let vel = particle_velocity.read(pos) + field.read(pos.extend(0)) * 1.0 / 30.0;
+
+            let p = pos.cast_f32() - vel * dt;
+            let px = p.x.clamp(0.5, GRID_SIZE as f32 - 1.5);
+            let py = p.y.clamp(0.5, GRID_SIZE as f32 - 1.5);
+
+            let i0 = px.floor();
+            let j0 = py.floor();
+            let s1 = px - i0;
+            let s0 = 1.0 - s1;
+            let t1 = py - j0;
+            let t0 = 1.0 - t1;
+
+            let i0 = i0.cast_u32();
+            let i1 = i0 + 1;
+            let j0 = j0.cast_u32();
+            let j1 = j0 + 1;
+
+            let v00 = particle_velocity.read(Vec2::expr(i0, j0));
+            let v01 = particle_velocity.read(Vec2::expr(i0, j1));
+            let v10 = particle_velocity.read(Vec2::expr(i1, j0));
+            let v11 = particle_velocity.read(Vec2::expr(i1, j1));
+            particle_velocity_next
+                .write(pos, s0 * (t0 * v00 + t1 * v01) + s1 * (t0 * v10 + t1 * v11));
+
+            let q00 = trail.read(Vec2::expr(i0, j0));
+            let q01 = trail.read(Vec2::expr(i0, j1));
+            let q10 = trail.read(Vec2::expr(i1, j0));
+            let q11 = trail.read(Vec2::expr(i1, j1));
+            trail_next.write(pos, s0 * (t0 * q00 + t1 * q01) + s1 * (t0 * q10 + t1 * q11));
+        }),
+    );
+    let copy_advected_kernel = Kernel::<fn()>::new(
+        &device,
+        &track!(|| {
+            let pos = dispatch_id().xy();
+            particle_velocity.write(pos, particle_velocity_next.read(pos));
+            trail.write(pos, trail_next.read(pos));
+        }),
+    );
     let update_trail_kernel = Kernel::<fn()>::new(
         &device,
         &track!(|| {
@@ -354,6 +634,12 @@ fn main() {
                 + magnets.read((pos + Vec2::y()).extend(level - 1))
                 + magnets.read((pos + Vec2::x() + Vec2::y()).extend(level - 1));
             magnets.write(target.extend(level), m);
+            // A coarse cell is solid if any of its four children are solid.
+            let s = (solids.read(pos.extend(level - 1)) == 1)
+                || (solids.read((pos + Vec2::x()).extend(level - 1)) == 1)
+                || (solids.read((pos + Vec2::y()).extend(level - 1)) == 1)
+                || (solids.read((pos + Vec2::x() + Vec2::y()).extend(level - 1)) == 1);
+            solids.write(target.extend(level), s.cast::<u32>());
         }),
     );
 
@@ -376,6 +662,179 @@ fn main() {
         }),
     );
 
+    let snapshot_fluid_velocity_kernel = Kernel::<fn()>::new(
+        &device,
+        &track!(|| {
+            let pos = dispatch_id().xy();
+            fluid_velocity_prev.write(pos, fluid_velocity.read(pos));
+        }),
+    );
+    // Red-black Gauss-Seidel solve of (I - a*laplacian) v = v0, with a = dt * viscosity * N^2,
+    // alternating parity across calls the same way compute_divergence does via `offset`.
+    let diffuse_velocity_kernel = Kernel::<fn(f32, u32)>::new(
+        &device,
+        &track!(|a, offset| {
+            let pos = dispatch_id().xy();
+            if (pos.x + pos.y) % 2 != offset {
+                return;
+            }
+            let v0 = fluid_velocity_prev.read(pos);
+            let self_v = fluid_velocity.read(pos);
+            let l = self_v.var();
+            if pos.x > 0 {
+                *l = fluid_velocity.read(pos - Vec2::x());
+            }
+            let r = self_v.var();
+            if pos.x + 1 < GRID_SIZE {
+                *r = fluid_velocity.read(pos + Vec2::x());
+            }
+            let u = self_v.var();
+            if pos.y > 0 {
+                *u = fluid_velocity.read(pos - Vec2::y());
+            }
+            let d = self_v.var();
+            if pos.y + 1 < GRID_SIZE {
+                *d = fluid_velocity.read(pos + Vec2::y());
+            }
+            fluid_velocity.write(pos, (v0 + a * (l + r + u + d)) / (1.0 + 4.0 * a));
+        }),
+    );
+    // Project step, part one: turn the velocity field's divergence into a Poisson source, feeding
+    // it to `charges` the same way user-painted charge normally feeds `target_divergence` in
+    // `compute_divergence`. The multigrid V-cycle below then solves `field` so that
+    // div(field) matches it, with `magnets` left at zero so `field` comes out curl-free, i.e. a
+    // pure gradient field.
+    let compute_fluid_divergence_kernel = Kernel::<fn()>::new(
+        &device,
+        &track!(|| {
+            let pos = dispatch_id().xy();
+            let self_v = fluid_velocity.read(pos);
+            let l = self_v.x.var();
+            if pos.x > 0 {
+                *l = fluid_velocity.read(pos - Vec2::x()).x;
+            }
+            let r = self_v.x.var();
+            if pos.x + 1 < GRID_SIZE {
+                *r = fluid_velocity.read(pos + Vec2::x()).x;
+            }
+            let u = self_v.y.var();
+            if pos.y > 0 {
+                *u = fluid_velocity.read(pos - Vec2::y()).y;
+            }
+            let d = self_v.y.var();
+            if pos.y + 1 < GRID_SIZE {
+                *d = fluid_velocity.read(pos + Vec2::y()).y;
+            }
+            let divergence = -0.5 * (r - l + d - u) / GRID_SIZE as f32;
+            charges.write(pos.extend(0), divergence);
+        }),
+    );
+    // Project step, part two: `field` now holds a pure gradient whose divergence is -div(v)/N (see
+    // above), so adding N * field to the velocity cancels that divergence and leaves the
+    // divergence-free part behind.
+    let apply_pressure_gradient_kernel = Kernel::<fn()>::new(
+        &device,
+        &track!(|| {
+            let pos = dispatch_id().xy();
+            fluid_velocity.write(
+                pos,
+                fluid_velocity.read(pos) + field.read(pos.extend(0)) * GRID_SIZE as f32,
+            );
+        }),
+    );
+    // Self-advection: the same semi-Lagrangian backtrace as `advect_kernel`, but the velocity
+    // field advects itself (and the dye `fluid_density` riding along with it) instead of being
+    // driven by the electromagnetic `field`.
+    let fluid_advect_kernel = Kernel::<fn(f32)>::new(
+        &device,
+        &track!(|dt| {
+            let pos = dispatch_id().xy();
+            let vel = fluid_velocity.read(pos);
+
+            let p = pos.cast_f32() - vel * dt;
+            let px = p.x.clamp(0.5, GRID_SIZE as f32 - 1.5);
+            let py = p.y.clamp(0.5, GRID_SIZE as f32 - 1.5);
+
+            let i0 = px.floor();
+            let j0 = py.floor();
+            let s1 = px - i0;
+            let s0 = 1.0 - s1;
+            let t1 = py - j0;
+            let t0 = 1.0 - t1;
+
+            let i0 = i0.cast_u32();
+            let i1 = i0 + 1;
+            let j0 = j0.cast_u32();
+            let j1 = j0 + 1;
+
+            let v00 = fluid_velocity.read(Vec2::expr(i0, j0));
+            let v01 = fluid_velocity.read(Vec2::expr(i0, j1));
+            let v10 = fluid_velocity.read(Vec2::expr(i1, j0));
+            let v11 = fluid_velocity.read(Vec2::expr(i1, j1));
+            fluid_velocity_next.write(pos, s0 * (t0 * v00 + t1 * v01) + s1 * (t0 * v10 + t1 * v11));
+
+            let q00 = fluid_density.read(Vec2::expr(i0, j0));
+            let q01 = fluid_density.read(Vec2::expr(i0, j1));
+            let q10 = fluid_density.read(Vec2::expr(i1, j0));
+            let q11 = fluid_density.read(Vec2::expr(i1, j1));
+            fluid_density_next.write(pos, s0 * (t0 * q00 + t1 * q01) + s1 * (t0 * q10 + t1 * q11));
+        }),
+    );
+    let copy_fluid_advected_kernel = Kernel::<fn()>::new(
+        &device,
+        &track!(|| {
+            let pos = dispatch_id().xy();
+            fluid_velocity.write(pos, fluid_velocity_next.read(pos));
+            fluid_density.write(pos, fluid_density_next.read(pos));
+        }),
+    );
+    let inject_fluid_kernel = Kernel::<fn(Vec2<u32>)>::new(
+        &device,
+        &track!(|pos| {
+            let p = pos + dispatch_id().xy();
+            fluid_density.write(p, 1.0);
+            fluid_velocity.write(p, fluid_velocity.read(p) + Vec2::expr(0.0, 2.0));
+        }),
+    );
+    let snapshot_em_kernel = Kernel::<fn()>::new(
+        &device,
+        &track!(|| {
+            let pos = dispatch_id().xy();
+            charges_snapshot.write(pos, charges.read(pos.extend(0)));
+            magnets_snapshot.write(pos, magnets.read(pos.extend(0)));
+        }),
+    );
+    let snapshot_field_kernel = Kernel::<fn(u32)>::new(
+        &device,
+        &track!(|level| {
+            let pos = dispatch_id().xy();
+            field_snapshot.write(pos.extend(level), field.read(pos.extend(level)));
+        }),
+    );
+    let restore_em_kernel = Kernel::<fn()>::new(
+        &device,
+        &track!(|| {
+            let pos = dispatch_id().xy();
+            charges.write(pos.extend(0), charges_snapshot.read(pos));
+            magnets.write(pos.extend(0), magnets_snapshot.read(pos));
+        }),
+    );
+    let restore_field_kernel = Kernel::<fn(u32)>::new(
+        &device,
+        &track!(|level| {
+            let pos = dispatch_id().xy();
+            field.write(pos.extend(level), field_snapshot.read(pos.extend(level)));
+        }),
+    );
+    // The pressure solve needs `field` to come out curl-free, so the magnets it consults must be
+    // zero; the real painted values are safe in `magnets_snapshot` until `restore_em_kernel` runs.
+    let clear_magnets_kernel = Kernel::<fn()>::new(
+        &device,
+        &track!(|| {
+            magnets.write(dispatch_id().xy().extend(0), 0.0);
+        }),
+    );
+
     let write_charge_kernel = Kernel::<fn(Vec2<u32>, f32)>::new(
         &device,
         &track!(|pos, value| {
@@ -394,24 +853,182 @@ fn main() {
             particles.write(pos + dispatch_id().xy(), 1);
         }),
     );
+    let write_solid_kernel = Kernel::<fn(Vec2<u32>)>::new(
+        &device,
+        &track!(|pos| {
+            solids.write((pos + dispatch_id().xy()).extend(0), 1);
+        }),
+    );
+
+    let write_soft_particle_kernel = Kernel::<fn(u32, Vec2<f32>)>::new(
+        &device,
+        &track!(|index, pos| {
+            soft_position.write(index, pos);
+            soft_position_prev.write(index, pos);
+        }),
+    );
+    let write_spring_kernel = Kernel::<fn(u32, Spring)>::new(
+        &device,
+        &track!(|index, spring| {
+            springs.write(index, spring);
+        }),
+    );
+    let clear_soft_force_kernel = Kernel::<fn()>::new(
+        &device,
+        &track!(|| {
+            let i = dispatch_id().x;
+            soft_force_x.write(i, 0.0);
+            soft_force_y.write(i, 0.0);
+        }),
+    );
+    // Hooke's law plus a damping term proportional to how fast the two ends are closing, with
+    // velocity estimated from the Verlet position history. Both ends of a spring can be shared
+    // with other springs, so the force accumulation has to be atomic.
+    let relax_springs_kernel = Kernel::<fn(u32)>::new(
+        &device,
+        &track!(|count| {
+            let i = dispatch_id().x;
+            if i >= count {
+                return;
+            }
+            let spring = springs.read(i);
+            let pa = soft_position.read(spring.a);
+            let pb = soft_position.read(spring.b);
+            let diff = pb - pa;
+            let dist = diff.length().max(1e-4);
+            let dir = diff / dist;
+            let va = pa - soft_position_prev.read(spring.a);
+            let vb = pb - soft_position_prev.read(spring.b);
+            let closing_speed = (vb - va).dot(dir);
+            let force =
+                (dist - spring.rest_length) * SPRING_STIFFNESS + closing_speed * SPRING_DAMPING;
+            let f = dir * force;
+            soft_force_x.atomic_fetch_add(spring.a, f.x);
+            soft_force_y.atomic_fetch_add(spring.a, f.y);
+            soft_force_x.atomic_fetch_add(spring.b, -f.x);
+            soft_force_y.atomic_fetch_add(spring.b, -f.y);
+        }),
+    );
+    // Verlet integration: p_new = 2p - p_prev + (F/m + field(p)) * dt^2. `field`, sampled at the
+    // node's cell, acts on soft-body nodes as an acceleration.
+    let integrate_kernel = Kernel::<fn(u32, f32)>::new(
+        &device,
+        &track!(|count, dt| {
+            let i = dispatch_id().x;
+            if i >= count {
+                return;
+            }
+            let p = soft_position.read(i);
+            let p_prev = soft_position_prev.read(i);
+            let force = Vec2::expr(soft_force_x.read(i), soft_force_y.read(i));
+            let cx = p.x.clamp(0.0, GRID_SIZE as f32 - 1.0).cast_u32();
+            let cy = p.y.clamp(0.0, GRID_SIZE as f32 - 1.0).cast_u32();
+            let accel = force / SOFT_BODY_MASS + field.read(Vec2::expr(cx, cy).extend(0));
+            let new_p = p * 2.0 - p_prev + accel * dt * dt;
+            let new_x = new_p.x.clamp(0.0, GRID_SIZE as f32 - 1.0);
+            let new_y = new_p.y.clamp(0.0, GRID_SIZE as f32 - 1.0);
+            soft_position_prev.write(i, p);
+            soft_position.write(i, Vec2::expr(new_x, new_y));
+        }),
+    );
+    let clear_soft_display_kernel = Kernel::<fn()>::new(
+        &device,
+        &track!(|| {
+            let pos = dispatch_id().xy();
+            particles.write(pos, 0);
+        }),
+    );
+    let rasterize_soft_kernel = Kernel::<fn(u32)>::new(
+        &device,
+        &track!(|count| {
+            let i = dispatch_id().x;
+            if i >= count {
+                return;
+            }
+            let pos = soft_position.read(i).cast_u32();
+            particles.write(pos, 1);
+            trail.write(pos, 1.0);
+        }),
+    );
 
     let mut active_buttons = HashSet::new();
 
-    let mut update_cursor = |active_buttons: &HashSet<MouseButton>, rt: &mut Runtime| {
-        let pos = Vec2::new(
-            (rt.cursor_pos.x as u32) / SCALING,
-            (rt.cursor_pos.y as u32) / SCALING,
-        );
-        if active_buttons.contains(&MouseButton::Left) {
-            write_charge_kernel.dispatch([1, 1, 1], &pos, &-CHARGE);
-        }
-        if active_buttons.contains(&MouseButton::Right) {
-            write_magnet_kernel.dispatch([1, 1, 1], &pos, &CHARGE);
-        }
-        if active_buttons.contains(&MouseButton::Middle) {
-            write_particle_kernel.dispatch([1, 1, 1], &pos);
-        }
-    };
+    // Soft-body host-side bookkeeping: how many nodes/springs have been placed so far, which grid
+    // cell each node sits at (so dragging over an existing node reuses it), and the last node
+    // touched during the current drag (so the next cell visited gets a spring to it).
+    let mut soft_particle_count: u32 = 0;
+    let mut soft_spring_count: u32 = 0;
+    let mut soft_node_at: HashMap<(u32, u32), u32> = HashMap::new();
+    let mut soft_drag_last: Option<((u32, u32), u32)> = None;
+
+    let mut update_cursor =
+        |active_buttons: &HashSet<MouseButton>,
+         rt: &mut Runtime,
+         soft_particle_count: &mut u32,
+         soft_spring_count: &mut u32,
+         soft_node_at: &mut HashMap<(u32, u32), u32>,
+         soft_drag_last: &mut Option<((u32, u32), u32)>| {
+            let pos = Vec2::new(
+                (rt.cursor_pos.x as u32) / SCALING,
+                (rt.cursor_pos.y as u32) / SCALING,
+            );
+            if active_buttons.contains(&MouseButton::Left) {
+                if rt.fluid_mode {
+                    inject_fluid_kernel.dispatch([1, 1, 1], &pos);
+                } else {
+                    write_charge_kernel.dispatch([1, 1, 1], &pos, &-CHARGE);
+                }
+            }
+            if active_buttons.contains(&MouseButton::Right) {
+                write_magnet_kernel.dispatch([1, 1, 1], &pos, &CHARGE);
+            }
+            if active_buttons.contains(&MouseButton::Middle) {
+                if rt.soft_body_mode {
+                    let cell = (pos.x, pos.y);
+                    let index = if let Some(&idx) = soft_node_at.get(&cell) {
+                        idx
+                    } else if *soft_particle_count < MAX_SOFT_PARTICLES {
+                        let idx = *soft_particle_count;
+                        *soft_particle_count += 1;
+                        soft_node_at.insert(cell, idx);
+                        write_soft_particle_kernel.dispatch(
+                            [1, 1, 1],
+                            &idx,
+                            &Vec2::new(pos.x as f32, pos.y as f32),
+                        );
+                        idx
+                    } else {
+                        *soft_drag_last = None;
+                        return;
+                    };
+                    if let Some((last_cell, last_index)) = *soft_drag_last {
+                        if last_index != index && *soft_spring_count < MAX_SPRINGS {
+                            let spring_idx = *soft_spring_count;
+                            *soft_spring_count += 1;
+                            let dx = cell.0 as f32 - last_cell.0 as f32;
+                            let dy = cell.1 as f32 - last_cell.1 as f32;
+                            write_spring_kernel.dispatch(
+                                [1, 1, 1],
+                                &spring_idx,
+                                &Spring {
+                                    a: last_index,
+                                    b: index,
+                                    rest_length: (dx * dx + dy * dy).sqrt(),
+                                },
+                            );
+                        }
+                    }
+                    *soft_drag_last = Some((cell, index));
+                } else {
+                    write_particle_kernel.dispatch([1, 1, 1], &pos);
+                }
+            } else if rt.soft_body_mode {
+                *soft_drag_last = None;
+            }
+            if active_buttons.contains(&MouseButton::Back) {
+                write_solid_kernel.dispatch([1, 1, 1], &pos);
+            }
+        };
     let update_cursor = &mut update_cursor;
 
     let mut update_keyboard = |ev: KeyEvent, rt: &mut Runtime| {
@@ -441,6 +1058,35 @@ fn main() {
             KeyCode::KeyM => {
                 rt.activate_multigrid = !rt.activate_multigrid;
             }
+            KeyCode::KeyA => {
+                rt.activate_advection = !rt.activate_advection;
+            }
+            KeyCode::KeyF => {
+                rt.fluid_mode = !rt.fluid_mode;
+            }
+            KeyCode::KeyB => {
+                rt.soft_body_mode = !rt.soft_body_mode;
+            }
+            KeyCode::Digit1 => {
+                rt.flock_separation_weight =
+                    (rt.flock_separation_weight - FLOCK_WEIGHT_STEP).max(0.0);
+            }
+            KeyCode::Digit2 => {
+                rt.flock_separation_weight += FLOCK_WEIGHT_STEP;
+            }
+            KeyCode::Digit3 => {
+                rt.flock_alignment_weight =
+                    (rt.flock_alignment_weight - FLOCK_WEIGHT_STEP).max(0.0);
+            }
+            KeyCode::Digit4 => {
+                rt.flock_alignment_weight += FLOCK_WEIGHT_STEP;
+            }
+            KeyCode::Digit5 => {
+                rt.flock_cohesion_weight = (rt.flock_cohesion_weight - FLOCK_WEIGHT_STEP).max(0.0);
+            }
+            KeyCode::Digit6 => {
+                rt.flock_cohesion_weight += FLOCK_WEIGHT_STEP;
+            }
             _ => (),
         }
     };
@@ -455,6 +1101,32 @@ fn main() {
 
     let mut parity = 0;
 
+    // The multigrid V-cycle that relaxes `field` toward div(field) = charges, curl(field) =
+    // magnets. Shared between the normal per-frame solve and the fluid subsystem's pressure
+    // projection, which feeds it a velocity-divergence source instead of painted charge.
+    let mut push_poisson_solve =
+        |commands: &mut Vec<_>, parity: &mut u32, activate_multigrid: bool| {
+            for i in 1..DOWNSCALE_COUNT {
+                let size = GRID_SIZE >> i;
+                commands.push(downscale_charges_kernel.dispatch_async([size, size, 1], &i));
+            }
+            for i in (0..DOWNSCALE_COUNT).rev() {
+                let size = GRID_SIZE >> i;
+                if activate_multigrid && i < DOWNSCALE_COUNT - 1 {
+                    commands.push(upscale_field_kernel.dispatch_async([size, size, 1], &i));
+                }
+                for _ in 0..16 {
+                    commands.extend([
+                        compute_divergence.dispatch_async([size, size, 1], &i, &*parity),
+                        compute_curl.dispatch_async([size - 1, size - 1, 1], &i),
+                        apply_deltas.dispatch_async([size + 1, size + 1, 1], &i),
+                    ]);
+                    *parity = 1 - *parity;
+                }
+            }
+        };
+    let push_poisson_solve = &mut push_poisson_solve;
+
     let mut avg_iter_time = 0.0;
 
     event_loop.set_control_flow(ControlFlow::Poll);
@@ -471,48 +1143,159 @@ fn main() {
                     if dt * rt.t < start.elapsed() {
                         let iter_st = Instant::now();
                         rt.t += 1;
-                        update_cursor(&active_buttons, &mut rt);
+                        update_cursor(
+                            &active_buttons,
+                            &mut rt,
+                            &mut soft_particle_count,
+                            &mut soft_spring_count,
+                            &mut soft_node_at,
+                            &mut soft_drag_last,
+                        );
                         {
                             let mut commands = vec![];
-                            for i in 1..DOWNSCALE_COUNT {
-                                let size = GRID_SIZE >> i;
+                            if rt.fluid_mode {
+                                // Save the electromagnetic state the projection below is about to
+                                // reuse, so it can be put back afterward instead of staying
+                                // clobbered (see the comment on `charges_snapshot`).
                                 commands.push(
-                                    downscale_charges_kernel.dispatch_async([size, size, 1], &i),
+                                    snapshot_em_kernel.dispatch_async([GRID_SIZE, GRID_SIZE, 1]),
                                 );
-                            }
-                            for i in (0..DOWNSCALE_COUNT).rev() {
-                                let size = GRID_SIZE >> i;
-                                if rt.activate_multigrid && i < DOWNSCALE_COUNT - 1 {
+                                for i in 0..DOWNSCALE_COUNT {
+                                    let size = (GRID_SIZE >> i) + 1;
                                     commands.push(
-                                        upscale_field_kernel.dispatch_async([size, size, 1], &i),
+                                        snapshot_field_kernel.dispatch_async([size, size, 1], &i),
                                     );
                                 }
+                                commands.push(
+                                    clear_magnets_kernel.dispatch_async([GRID_SIZE, GRID_SIZE, 1]),
+                                );
+                                commands.push(
+                                    snapshot_fluid_velocity_kernel
+                                        .dispatch_async([GRID_SIZE, GRID_SIZE, 1]),
+                                );
+                                // Diffusion relaxes toward (I - a*laplacian) v = v0, where
+                                // a = dt * viscosity * N^2 (see diffuse_velocity_kernel's doc
+                                // comment); passing viscosity alone left `a` far too small to do
+                                // anything.
+                                let diffuse_a =
+                                    step * FLUID_VISCOSITY * (GRID_SIZE * GRID_SIZE) as f32;
                                 for _ in 0..16 {
-                                    commands.extend([
-                                        compute_divergence.dispatch_async(
-                                            [size, size, 1],
-                                            &i,
-                                            &parity,
-                                        ),
-                                        compute_curl.dispatch_async([size - 1, size - 1, 1], &i),
-                                        apply_deltas.dispatch_async([size + 1, size + 1, 1], &i),
-                                    ]);
+                                    commands.push(diffuse_velocity_kernel.dispatch_async(
+                                        [GRID_SIZE, GRID_SIZE, 1],
+                                        &diffuse_a,
+                                        &parity,
+                                    ));
                                     parity = 1 - parity;
                                 }
+                                commands.push(
+                                    compute_fluid_divergence_kernel
+                                        .dispatch_async([GRID_SIZE, GRID_SIZE, 1]),
+                                );
+                                push_poisson_solve(
+                                    &mut commands,
+                                    &mut parity,
+                                    rt.activate_multigrid,
+                                );
+                                commands.push(
+                                    apply_pressure_gradient_kernel
+                                        .dispatch_async([GRID_SIZE, GRID_SIZE, 1]),
+                                );
+                                commands.extend([
+                                    fluid_advect_kernel
+                                        .dispatch_async([GRID_SIZE, GRID_SIZE, 1], &step),
+                                    copy_fluid_advected_kernel
+                                        .dispatch_async([GRID_SIZE, GRID_SIZE, 1]),
+                                ]);
+                                commands.push(
+                                    compute_fluid_divergence_kernel
+                                        .dispatch_async([GRID_SIZE, GRID_SIZE, 1]),
+                                );
+                                push_poisson_solve(
+                                    &mut commands,
+                                    &mut parity,
+                                    rt.activate_multigrid,
+                                );
+                                commands.push(
+                                    apply_pressure_gradient_kernel
+                                        .dispatch_async([GRID_SIZE, GRID_SIZE, 1]),
+                                );
+                                // Put the electromagnetic state back the way the projection found
+                                // it; `field`/`charges`/`magnets` are shared with update_kernel,
+                                // advect_kernel, the soft-body integrator and draw_kernel below, so the EM
+                                // subsystem must come back untouched, not stay overwritten.
+                                commands.push(
+                                    restore_em_kernel.dispatch_async([GRID_SIZE, GRID_SIZE, 1]),
+                                );
+                                for i in 0..DOWNSCALE_COUNT {
+                                    let size = (GRID_SIZE >> i) + 1;
+                                    commands.push(
+                                        restore_field_kernel.dispatch_async([size, size, 1], &i),
+                                    );
+                                }
+                            } else {
+                                push_poisson_solve(
+                                    &mut commands,
+                                    &mut parity,
+                                    rt.activate_multigrid,
+                                );
+                            }
+                            commands.push(flock_kernel.dispatch_async(
+                                [GRID_SIZE, GRID_SIZE, 1],
+                                &rt.flock_separation_weight,
+                                &rt.flock_alignment_weight,
+                                &rt.flock_cohesion_weight,
+                            ));
+                            commands.push(update_kernel.dispatch_async(
+                                [GRID_SIZE, GRID_SIZE, 1],
+                                &step,
+                                &rt.t,
+                            ));
+                            if rt.activate_advection {
+                                commands.extend([
+                                    advect_kernel.dispatch_async([GRID_SIZE, GRID_SIZE, 1], &step),
+                                    copy_advected_kernel.dispatch_async([GRID_SIZE, GRID_SIZE, 1]),
+                                ]);
                             }
-                            commands.extend([
-                                update_kernel.dispatch_async(
-                                    [GRID_SIZE, GRID_SIZE, 1],
-                                    &step,
-                                    &rt.t,
-                                ),
+                            // Always reseed `trail` at the particles' (just-updated) cells and
+                            // decay it elsewhere; advection only smooths `trail` in transit above,
+                            // it doesn't reseed it, so skipping this when advection is on left the
+                            // trail with no source and it faded to black.
+                            commands.push(
                                 update_trail_kernel.dispatch_async([GRID_SIZE, GRID_SIZE, 1]),
-                                draw_kernel.dispatch_async(
-                                    [GRID_SIZE * SCALING, GRID_SIZE * SCALING, 1],
-                                    &rt.viewed_layer,
-                                    &rt.view,
-                                ),
-                            ]);
+                            );
+                            if rt.soft_body_mode {
+                                for _ in 0..SOFT_BODY_SUBSTEPS {
+                                    commands.push(clear_soft_force_kernel.dispatch_async([
+                                        MAX_SOFT_PARTICLES,
+                                        1,
+                                        1,
+                                    ]));
+                                    commands.push(
+                                        relax_springs_kernel.dispatch_async(
+                                            [MAX_SPRINGS, 1, 1],
+                                            &soft_spring_count,
+                                        ),
+                                    );
+                                    commands.push(integrate_kernel.dispatch_async(
+                                        [MAX_SOFT_PARTICLES, 1, 1],
+                                        &soft_particle_count,
+                                        &SOFT_BODY_SUBSTEP_DT,
+                                    ));
+                                }
+                                commands.push(
+                                    clear_soft_display_kernel
+                                        .dispatch_async([GRID_SIZE, GRID_SIZE, 1]),
+                                );
+                                commands.push(rasterize_soft_kernel.dispatch_async(
+                                    [MAX_SOFT_PARTICLES, 1, 1],
+                                    &soft_particle_count,
+                                ));
+                            }
+                            commands.extend([draw_kernel.dispatch_async(
+                                [GRID_SIZE * SCALING, GRID_SIZE * SCALING, 1],
+                                &rt.viewed_layer,
+                                &rt.view,
+                            )]);
                             scope.submit(commands);
                         }
                         avg_iter_time = avg_iter_time * 0.9 + iter_st.elapsed().as_secs_f64() * 0.1;
@@ -524,7 +1307,14 @@ fn main() {
                 }
                 WindowEvent::CursorMoved { position, .. } => {
                     rt.cursor_pos = position;
-                    update_cursor(&active_buttons, &mut rt);
+                    update_cursor(
+                        &active_buttons,
+                        &mut rt,
+                        &mut soft_particle_count,
+                        &mut soft_spring_count,
+                        &mut soft_node_at,
+                        &mut soft_drag_last,
+                    );
                 }
                 WindowEvent::MouseInput { button, state, .. } => {
                     match state {
@@ -535,7 +1325,14 @@ fn main() {
                             active_buttons.remove(&button);
                         }
                     }
-                    update_cursor(&active_buttons, &mut rt);
+                    update_cursor(
+                        &active_buttons,
+                        &mut rt,
+                        &mut soft_particle_count,
+                        &mut soft_spring_count,
+                        &mut soft_node_at,
+                        &mut soft_drag_last,
+                    );
                 }
                 WindowEvent::KeyboardInput { event, .. } => {
                     update_keyboard(event, &mut rt);